@@ -0,0 +1,62 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+//
+// Some portions of this file are taken and/or modified from Rumble
+// (https://github.com/mwylde/rumble), using a dual MIT/Apache License under the
+// following copyright:
+//
+// Copyright (c) 2014 The Rust Project Developers
+
+use std::convert::TryFrom;
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// A Bluetooth device address.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BDAddr {
+    address: [u8; 6],
+}
+
+/// A `u64` didn't fit the 48 bits of a Bluetooth address.
+pub struct AddressParseError;
+
+impl Debug for AddressParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "value does not fit a 48-bit Bluetooth address")
+    }
+}
+
+impl TryFrom<u64> for BDAddr {
+    type Error = AddressParseError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        if value > 0xFFFF_FFFF_FFFF {
+            return Err(AddressParseError);
+        }
+        let bytes = value.to_be_bytes();
+        let mut address = [0u8; 6];
+        address.copy_from_slice(&bytes[2..8]);
+        Ok(BDAddr { address })
+    }
+}
+
+impl From<BDAddr> for u64 {
+    fn from(addr: BDAddr) -> Self {
+        let [a, b, c, d, e, f] = addr.address;
+        u64::from_be_bytes([0, 0, a, b, c, d, e, f])
+    }
+}
+
+impl Display for BDAddr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let [a, b, c, d, e, f_] = self.address;
+        write!(
+            f,
+            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+            a, b, c, d, e, f_
+        )
+    }
+}
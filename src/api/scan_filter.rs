@@ -0,0 +1,27 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+//
+// Some portions of this file are taken and/or modified from Rumble
+// (https://github.com/mwylde/rumble), using a dual MIT/Apache License under the
+// following copyright:
+//
+// Copyright (c) 2014 The Rust Project Developers
+
+use uuid::Uuid;
+
+/// Criteria a backend's `start_scan`/`connected_peripherals` evaluates against each
+/// advertisement/device. A device is admitted if it satisfies at least one populated positive
+/// field (`services`, `name_pattern`, `manufacturer_data`, `min_rssi`) and matches no entry in
+/// `blocked_services`. All-default admits everything.
+#[derive(Clone, Debug, Default)]
+pub struct ScanFilter {
+    pub services: Vec<Uuid>,
+    pub blocked_services: Vec<Uuid>,
+    pub name_pattern: Option<String>,
+    pub manufacturer_data: Option<(u16, Vec<u8>)>,
+    pub min_rssi: Option<i16>,
+}
@@ -0,0 +1,40 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+//
+// Some portions of this file are taken and/or modified from Rumble
+// (https://github.com/mwylde/rumble), using a dual MIT/Apache License under the
+// following copyright:
+//
+// Copyright (c) 2014 The Rust Project Developers
+
+use std::fmt::{self, Display, Formatter};
+
+/// Errors that can occur while interacting with a Bluetooth adapter or peripheral.
+#[derive(Debug)]
+pub enum Error {
+    /// The requested peripheral is no longer known to the adapter.
+    DeviceNotFound,
+    /// A WinRT operation didn't complete within its allotted timeout.
+    Timeout,
+    /// A WinRT call failed in some other way, or returned a value this crate can't use; the
+    /// message is usually the WinRT error's formatted `Debug` output.
+    Other(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::DeviceNotFound => write!(f, "no such peripheral"),
+            Error::Timeout => write!(f, "operation timed out"),
+            Error::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
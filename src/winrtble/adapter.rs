@@ -11,7 +11,6 @@
 //
 // Copyright (c) 2014 The Rust Project Developers
 
-use uuid::Uuid;
 use super::{ble::watcher::BLEWatcher, peripheral::Peripheral, peripheral::PeripheralId};
 use crate::{
     api::{BDAddr, Central, CentralEvent, ScanFilter},
@@ -24,21 +23,276 @@ use std::convert::TryInto;
 use std::fmt::{self, Debug, Formatter};
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use windows::Devices::Bluetooth::Advertisement::BluetoothLEAdvertisementReceivedEventArgs;
+use windows::Devices::Bluetooth::BluetoothAdapter;
+use windows::Devices::Bluetooth::BluetoothConnectionStatus;
 use windows::Devices::Bluetooth::BluetoothLEDevice;
 use windows::Devices::Enumeration::DeviceInformation;
+use windows::Devices::Radios::{Radio, RadioState};
+use windows::Foundation::{
+    AsyncStatus, EventRegistrationToken, IAsyncOperation, TypedEventHandler,
+};
+
+/// Converts a WinRT error into the catch-all [Error::Other], the way this backend reports
+/// failures from the underlying WinRT APIs that don't map onto a more specific [Error] variant.
+pub(crate) fn win_error<T: Debug>(e: T) -> Error {
+    Error::Other(format!("{:?}", e).into())
+}
+
+/// The subset of an advertisement's contents that [ScanFilter] can match against, pulled out of
+/// the WinRT event args so the matching logic in [evaluate_filter] can be unit tested without a
+/// real radio.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct AdvertisementSnapshot {
+    service_uuids: Vec<Uuid>,
+    local_name: Option<String>,
+    manufacturer_data: Option<(u16, Vec<u8>)>,
+    rssi: Option<i16>,
+}
+
+impl AdvertisementSnapshot {
+    fn from_args(args: &BluetoothLEAdvertisementReceivedEventArgs) -> Option<Self> {
+        let advertisement = args.Advertisement().ok()?;
+
+        let service_uuids = advertisement
+            .ServiceUuids()
+            .map(|list| {
+                list.into_iter()
+                    .map(|uuid| Uuid::from_u128(uuid.to_u128()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let local_name = advertisement.LocalName().ok().map(|name| name.to_string());
+
+        let manufacturer_data = advertisement
+            .ManufacturerData()
+            .ok()
+            .and_then(|list| list.into_iter().next())
+            .and_then(|data| {
+                let company_id = data.CompanyId().ok()?;
+                let buffer = data.Data().ok()?;
+                let mut bytes = vec![0u8; buffer.Length().unwrap_or(0) as usize];
+                windows::Storage::Streams::DataReader::FromBuffer(&buffer)
+                    .and_then(|reader| reader.ReadBytes(&mut bytes))
+                    .ok()?;
+                Some((company_id, bytes))
+            });
+
+        let rssi = args.RawSignalStrengthInDBm().ok();
+
+        Some(AdvertisementSnapshot {
+            service_uuids,
+            local_name,
+            manufacturer_data,
+            rssi,
+        })
+    }
+}
+
+/// Evaluates `filter`'s positive and negative criteria against `advertisement`: the device
+/// passes only if it satisfies at least one populated positive field (services/name/manufacturer
+/// data/RSSI) and matches no entry in `blocked_services`. A filter with no positive criteria at
+/// all admits everything that isn't blocklisted; an all-default `ScanFilter` admits everything.
+fn evaluate_filter(filter: &ScanFilter, advertisement: &AdvertisementSnapshot) -> bool {
+    if advertisement
+        .service_uuids
+        .iter()
+        .any(|uuid| filter.blocked_services.contains(uuid))
+    {
+        return false;
+    }
+
+    let mut has_positive_filter = false;
+    let mut matched = false;
+
+    if !filter.services.is_empty() {
+        has_positive_filter = true;
+        matched |= advertisement
+            .service_uuids
+            .iter()
+            .any(|uuid| filter.services.contains(uuid));
+    }
+
+    if let Some(pattern) = &filter.name_pattern {
+        has_positive_filter = true;
+        if let Some(name) = &advertisement.local_name {
+            matched |= name.contains(pattern.as_str());
+        }
+    }
+
+    if let Some((company_id, data_prefix)) = &filter.manufacturer_data {
+        has_positive_filter = true;
+        if let Some((advertised_company_id, data)) = &advertisement.manufacturer_data {
+            matched |= advertised_company_id == company_id && data.starts_with(data_prefix);
+        }
+    }
+
+    if let Some(min_rssi) = filter.min_rssi {
+        has_positive_filter = true;
+        matched |= advertisement
+            .rssi
+            .map(|rssi| rssi >= min_rssi)
+            .unwrap_or(false);
+    }
+
+    !has_positive_filter || matched
+}
+
+/// Evaluates `filter` against a live advertisement event, extracting the fields
+/// [evaluate_filter] matches on and rejecting the advertisement if it can't be read at all.
+fn passes_filter(args: &BluetoothLEAdvertisementReceivedEventArgs, filter: &ScanFilter) -> bool {
+    match AdvertisementSnapshot::from_args(args) {
+        Some(snapshot) => evaluate_filter(filter, &snapshot),
+        None => false,
+    }
+}
+
+/// The power state of the underlying Bluetooth radio, as reported by [Adapter::adapter_state].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AdapterState {
+    PoweredOn,
+    PoweredOff,
+    Unavailable,
+}
+
+impl From<RadioState> for AdapterState {
+    fn from(state: RadioState) -> Self {
+        match state {
+            RadioState::On => AdapterState::PoweredOn,
+            RadioState::Off | RadioState::Disabled => AdapterState::PoweredOff,
+            _ => AdapterState::Unavailable,
+        }
+    }
+}
+
+/// The GATT spec treats a transaction that hasn't completed within 30 seconds as failed; this is
+/// the default bound on every WinRT async operation awaited by [Adapter] (and, via
+/// [get_with_timeout_blocking], by [Advertiser](super::advertiser::Advertiser)), so an
+/// unresponsive peripheral can't hang a caller forever.
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Blocks on `op`, polling its status instead of calling `IAsyncOperation::get` directly so a
+/// stalled WinRT call can be bounded by `timeout` rather than blocking indefinitely. Cancels the
+/// operation and returns [Error::Timeout] if it hasn't completed in time.
+///
+/// This busy-polls the calling thread, so it's only safe to call directly from a synchronous
+/// context (e.g. adapter construction); async call sites should go through [get_with_timeout].
+pub(crate) fn get_with_timeout_blocking<T: windows::core::RuntimeType + 'static>(
+    op: windows::core::Result<IAsyncOperation<T>>,
+    timeout: Duration,
+) -> Result<T> {
+    let op = op.map_err(win_error)?;
+    let start = Instant::now();
+    loop {
+        match op.Status() {
+            Ok(AsyncStatus::Completed) | Ok(AsyncStatus::Error) | Ok(AsyncStatus::Canceled) => {
+                return op.GetResults().map_err(win_error);
+            }
+            _ => {}
+        }
+        if start.elapsed() >= timeout {
+            let _ = op.Cancel();
+            return Err(Error::Timeout);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Async wrapper around [get_with_timeout_blocking] that runs the busy-poll on a blocking-pool
+/// thread via `spawn_blocking`, so a stalled WinRT call ties up a blocking-pool thread instead of
+/// the async executor thread running the caller's task.
+async fn get_with_timeout<T: windows::core::RuntimeType + Send + 'static>(
+    op: windows::core::Result<IAsyncOperation<T>>,
+    timeout: Duration,
+) -> Result<T> {
+    tokio::task::spawn_blocking(move || get_with_timeout_blocking(op, timeout))
+        .await
+        .map_err(win_error)?
+}
+
+/// Keeps a `Radio` and its `StateChanged` registration token alive together, unsubscribing when
+/// dropped. WinRT drops an event subscription as soon as its source object goes away, so a
+/// `Radio` that's merely local to `watch_radio_state` would stop firing `StateChanged` the
+/// moment that call returns.
+struct RadioSubscription {
+    radio: Radio,
+    token: EventRegistrationToken,
+}
+
+impl Drop for RadioSubscription {
+    fn drop(&mut self) {
+        let _ = self.radio.RemoveStateChanged(self.token);
+    }
+}
 
 /// Implementation of [api::Central](crate::api::Central).
 #[derive(Clone)]
 pub struct Adapter {
     watcher: Arc<Mutex<BLEWatcher>>,
     manager: Arc<AdapterManager<Peripheral>>,
+    radio: Arc<Mutex<Option<RadioSubscription>>>,
+    timeout: Duration,
 }
 
 impl Adapter {
     pub(crate) fn new() -> Self {
+        Self::with_timeout(DEFAULT_TIMEOUT)
+    }
+
+    /// Builds an `Adapter` whose WinRT operations are bounded by `timeout` instead of the
+    /// default 30 seconds.
+    pub fn with_timeout(timeout: Duration) -> Self {
         let watcher = Arc::new(Mutex::new(BLEWatcher::new()));
         let manager = Arc::new(AdapterManager::default());
-        Adapter { watcher, manager }
+        let adapter = Adapter {
+            watcher,
+            manager,
+            radio: Arc::new(Mutex::new(None)),
+            timeout,
+        };
+        adapter.watch_radio_state();
+        adapter
+    }
+
+    /// Subscribes to the default Bluetooth radio's `StateChanged` event and forwards toggles as
+    /// [CentralEvent::StateUpdate], so callers find out about radios being switched off/on
+    /// without having to poll [Adapter::adapter_state] themselves. The `Radio` is kept alive in
+    /// `self.radio` for as long as the subscription should keep firing.
+    fn watch_radio_state(&self) {
+        let manager = self.manager.clone();
+        if let Ok(adapter) =
+            get_with_timeout_blocking(BluetoothAdapter::GetDefaultAsync(), self.timeout)
+        {
+            if let Ok(radio) = get_with_timeout_blocking(adapter.GetRadioAsync(), self.timeout) {
+                if let Ok(token) = radio.StateChanged(&TypedEventHandler::new(move |_, _| {
+                    manager.emit(CentralEvent::StateUpdate);
+                    Ok(())
+                })) {
+                    *self.radio.lock().unwrap() = Some(RadioSubscription { radio, token });
+                }
+            }
+        }
+    }
+
+    /// Returns the current power state of the default Bluetooth radio.
+    pub async fn adapter_state(&self) -> Result<AdapterState> {
+        let adapter = get_with_timeout(BluetoothAdapter::GetDefaultAsync(), self.timeout).await?;
+        let radio = get_with_timeout(adapter.GetRadioAsync(), self.timeout).await?;
+        Ok(radio.State().map_err(win_error)?.into())
+    }
+
+    /// Resolves once the default Bluetooth radio is powered on and ready. Callers can use this
+    /// to block until Bluetooth is usable instead of failing opaquely mid-scan.
+    pub async fn wait_available(&self) -> Result<()> {
+        loop {
+            match self.adapter_state().await? {
+                AdapterState::PoweredOn => return Ok(()),
+                _ => tokio::time::sleep(Duration::from_millis(500)).await,
+            }
+        }
     }
 }
 
@@ -58,74 +312,103 @@ impl Central for Adapter {
         Ok(self.manager.event_stream())
     }
 
-    async fn connected_peripherals(&self, filter: ScanFilter) -> Result<()> {
-        /* TODO unwrap is unsafe. */
-        /* TODO filter for MouthPad and return that. */
-        let service_filter = filter.services[0];
-        let devices = match DeviceInformation::FindAllAsyncAqsFilter(
-            &BluetoothLEDevice::GetDeviceSelector().unwrap(),
+    /// Enumerates devices the OS already considers paired/connected, rather than relying on a
+    /// fresh advertisement scan. This is the only reliable way to find bonded peripherals on
+    /// Windows, since many of them stop advertising once connected.
+    async fn connected_peripherals(&self, filter: ScanFilter) -> Result<Vec<Peripheral>> {
+        let selector = BluetoothLEDevice::GetDeviceSelectorFromConnectionStatus(
+            BluetoothConnectionStatus::Connected,
         )
-        .unwrap()
-        .get()
-        {
-            Ok(devices) => devices,
-            Err(e) => {
-                return Err(Error::Other(format!("{:?}", e).into()));
-            }
-        };
+        .map_err(win_error)?;
+        let devices = get_with_timeout(
+            DeviceInformation::FindAllAsyncAqsFilter(&selector),
+            self.timeout,
+        )
+        .await?;
         let manager = self.manager.clone();
+        let mut found = Vec::new();
 
         for device in devices {
-            let device_id = device.Id().unwrap();
-            println!("Device ID: {:?}", device_id);
-            let ble_device = match BluetoothLEDevice::FromIdAsync(&device_id) {
-                Ok(ble_device) => ble_device,
-                Err(e) => {
-                    println!("Error getting ble device from id: {:?}", e);
-                    continue;
-                }
+            let device_id = match device.Id() {
+                Ok(id) => id,
+                Err(_) => continue,
             };
-            let ble_device = match ble_device.get() {
-                Ok(ble_device) => ble_device,
-                Err(e) => {
-                    println!("Error getting ble device: {:?}", e);
-                    continue;
-                }
+            let ble_device =
+                match get_with_timeout(BluetoothLEDevice::FromIdAsync(&device_id), self.timeout)
+                    .await
+                {
+                    Ok(ble_device) => ble_device,
+                    Err(_) => continue,
+                };
+            let services = match get_with_timeout(ble_device.GetGattServicesAsync(), self.timeout)
+                .await
+                .and_then(|result| result.Services().map_err(win_error))
+            {
+                Ok(services) => services,
+                Err(_) => continue,
             };
-            let services = ble_device
-                .GetGattServicesAsync()
-                .unwrap()
-                .get()
-                .unwrap()
-                .Services()
-                .unwrap();
-            println!("got services");
-            for service in services {
-                println!("Service: {:?}", service.Uuid().unwrap());
-                let service_uuid = Uuid::from_u128(service.Uuid().unwrap().to_u128());
-                if service_uuid == service_filter {
-                    let bluetooth_address = ble_device.BluetoothAddress().unwrap();
-                    let address: BDAddr = bluetooth_address.try_into().unwrap();
-                    let peripheral = Peripheral::new(Arc::downgrade(&manager), address);
-                    // TODO this populates things like the device name
-                    // peripheral.update_properties(args);
-                    manager.add_peripheral(peripheral);
-                    manager.emit(CentralEvent::DeviceDiscovered(address.into()));
-                    return Ok(());
-                }
+            let service_uuids: Vec<Uuid> = services
+                .into_iter()
+                .filter_map(|service| service.Uuid().ok())
+                .map(|uuid| Uuid::from_u128(uuid.to_u128()))
+                .collect();
+
+            let local_name = ble_device.Name().ok().map(|name| name.to_string());
+
+            // A connected device doesn't carry a live advertisement, so the snapshot can only
+            // speak to services/name; manufacturer data and RSSI are left unset and simply can't
+            // satisfy those parts of the filter.
+            let snapshot = AdvertisementSnapshot {
+                service_uuids: service_uuids.clone(),
+                local_name: local_name.clone(),
+                manufacturer_data: None,
+                rssi: None,
+            };
+            if !evaluate_filter(&filter, &snapshot) {
+                continue;
             }
+
+            let bluetooth_address = match ble_device.BluetoothAddress() {
+                Ok(address) => address,
+                Err(_) => continue,
+            };
+            let address: BDAddr = match bluetooth_address.try_into() {
+                Ok(address) => address,
+                Err(_) => continue,
+            };
+
+            let peripheral = Peripheral::new(Arc::downgrade(&manager), address);
+            if let Some(name) = local_name {
+                peripheral.update_name(&name);
+            }
+            peripheral.update_services(&service_uuids);
+
+            manager.add_peripheral(peripheral.clone());
+            manager.emit(CentralEvent::DeviceDiscovered(address.into()));
+            found.push(peripheral);
         }
-        Ok(())
+
+        Ok(found)
     }
 
     async fn start_scan(&self, filter: ScanFilter) -> Result<()> {
         let watcher = self.watcher.lock().unwrap();
         let manager = self.manager.clone();
+        let predicate_filter = filter.clone();
         watcher.start(
             filter,
             Box::new(move |args| {
-                let bluetooth_address = args.BluetoothAddress().unwrap();
-                let address: BDAddr = bluetooth_address.try_into().unwrap();
+                if !passes_filter(args, &predicate_filter) {
+                    return;
+                }
+                let bluetooth_address = match args.BluetoothAddress() {
+                    Ok(address) => address,
+                    Err(_) => return,
+                };
+                let address: BDAddr = match bluetooth_address.try_into() {
+                    Ok(address) => address,
+                    Err(_) => return,
+                };
                 if let Some(mut entry) = manager.peripheral_mut(&address.into()) {
                     entry.value_mut().update_properties(args);
                     manager.emit(CentralEvent::DeviceUpdated(address.into()));
@@ -153,14 +436,160 @@ impl Central for Adapter {
         self.manager.peripheral(id).ok_or(Error::DeviceNotFound)
     }
 
-    async fn add_peripheral(&self, _address: &PeripheralId) -> Result<Peripheral> {
-        Err(Error::NotSupported(
-            "Can't add a Peripheral from a BDAddr".to_string(),
-        ))
+    /// Builds and registers a [Peripheral] for a device the caller already knows about (e.g. a
+    /// [PeripheralId] persisted from a previous session), so it can be connected to without a
+    /// live scan to rediscover it first. This does not connect to the device.
+    async fn add_peripheral(&self, id: &PeripheralId) -> Result<Peripheral> {
+        let address: BDAddr = id.clone().into();
+        let bluetooth_address: u64 = address.into();
+
+        let ble_device = get_with_timeout(
+            BluetoothLEDevice::FromBluetoothAddressAsync(bluetooth_address),
+            self.timeout,
+        )
+        .await?;
+
+        // A bluetooth address with no matching device yields a null WinRT reference rather than
+        // an error, so surface that as DeviceNotFound.
+        if ble_device.BluetoothAddress().is_err() {
+            return Err(Error::DeviceNotFound);
+        }
+
+        let peripheral = Peripheral::new(Arc::downgrade(&self.manager), address);
+        self.manager.add_peripheral(peripheral.clone());
+        Ok(peripheral)
     }
 
     async fn adapter_info(&self) -> Result<String> {
-        // TODO: Get information about the adapter.
-        Ok("WinRT".to_string())
+        let adapter = get_with_timeout(BluetoothAdapter::GetDefaultAsync(), self.timeout).await?;
+        let address: BDAddr = adapter
+            .BluetoothAddress()
+            .map_err(win_error)?
+            .try_into()
+            .map_err(|_| Error::Other("adapter reported a malformed address".into()))?;
+        let device_id = adapter.DeviceId().map_err(win_error)?;
+        let name = get_with_timeout(
+            DeviceInformation::CreateFromIdAsync(&device_id),
+            self.timeout,
+        )
+        .await
+        .and_then(|info| info.Name().map_err(win_error))
+        .map(|name| name.to_string())
+        .unwrap_or_default();
+        Ok(format!(
+            "{} ({}), LE: {}, central: {}, peripheral: {}",
+            name,
+            address,
+            adapter.IsLowEnergySupported().unwrap_or(false),
+            adapter.IsCentralRoleSupported().unwrap_or(false),
+            adapter.IsPeripheralRoleSupported().unwrap_or(false),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> AdvertisementSnapshot {
+        AdvertisementSnapshot {
+            service_uuids: vec![Uuid::from_u128(1), Uuid::from_u128(2)],
+            local_name: Some("widget-42".to_string()),
+            manufacturer_data: Some((0x004C, vec![0x02, 0x15, 0xAA])),
+            rssi: Some(-60),
+        }
+    }
+
+    #[test]
+    fn empty_filter_admits_everything() {
+        assert!(evaluate_filter(&ScanFilter::default(), &snapshot()));
+    }
+
+    #[test]
+    fn blocked_service_is_rejected_even_if_otherwise_matching() {
+        let filter = ScanFilter {
+            blocked_services: vec![Uuid::from_u128(1)],
+            ..ScanFilter::default()
+        };
+        assert!(!evaluate_filter(&filter, &snapshot()));
+    }
+
+    #[test]
+    fn service_filter_requires_a_match() {
+        let matching = ScanFilter {
+            services: vec![Uuid::from_u128(2)],
+            ..ScanFilter::default()
+        };
+        assert!(evaluate_filter(&matching, &snapshot()));
+
+        let non_matching = ScanFilter {
+            services: vec![Uuid::from_u128(99)],
+            ..ScanFilter::default()
+        };
+        assert!(!evaluate_filter(&non_matching, &snapshot()));
+    }
+
+    #[test]
+    fn name_pattern_must_match() {
+        let matching = ScanFilter {
+            name_pattern: Some("widget".to_string()),
+            ..ScanFilter::default()
+        };
+        assert!(evaluate_filter(&matching, &snapshot()));
+
+        let non_matching = ScanFilter {
+            name_pattern: Some("gadget".to_string()),
+            ..ScanFilter::default()
+        };
+        assert!(!evaluate_filter(&non_matching, &snapshot()));
+    }
+
+    #[test]
+    fn manufacturer_data_must_match_company_id_and_prefix() {
+        let matching = ScanFilter {
+            manufacturer_data: Some((0x004C, vec![0x02, 0x15])),
+            ..ScanFilter::default()
+        };
+        assert!(evaluate_filter(&matching, &snapshot()));
+
+        let wrong_company = ScanFilter {
+            manufacturer_data: Some((0x0001, vec![0x02, 0x15])),
+            ..ScanFilter::default()
+        };
+        assert!(!evaluate_filter(&wrong_company, &snapshot()));
+
+        let wrong_prefix = ScanFilter {
+            manufacturer_data: Some((0x004C, vec![0xFF])),
+            ..ScanFilter::default()
+        };
+        assert!(!evaluate_filter(&wrong_prefix, &snapshot()));
+    }
+
+    #[test]
+    fn min_rssi_is_a_cutoff() {
+        let passes = ScanFilter {
+            min_rssi: Some(-70),
+            ..ScanFilter::default()
+        };
+        assert!(evaluate_filter(&passes, &snapshot()));
+
+        let rejects = ScanFilter {
+            min_rssi: Some(-50),
+            ..ScanFilter::default()
+        };
+        assert!(!evaluate_filter(&rejects, &snapshot()));
+    }
+
+    #[test]
+    fn adapter_state_from_radio_state() {
+        assert_eq!(AdapterState::from(RadioState::On), AdapterState::PoweredOn);
+        assert_eq!(
+            AdapterState::from(RadioState::Off),
+            AdapterState::PoweredOff
+        );
+        assert_eq!(
+            AdapterState::from(RadioState::Disabled),
+            AdapterState::PoweredOff
+        );
     }
 }
@@ -0,0 +1,288 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+//
+// Some portions of this file are taken and/or modified from Rumble
+// (https://github.com/mwylde/rumble), using a dual MIT/Apache License under the
+// following copyright:
+//
+// Copyright (c) 2014 The Rust Project Developers
+
+use super::adapter::{get_with_timeout_blocking, win_error, DEFAULT_TIMEOUT};
+use crate::Result;
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+use windows::core::GUID;
+use windows::Devices::Bluetooth::Advertisement::{
+    BluetoothLEAdvertisement, BluetoothLEAdvertisementPublisher,
+    BluetoothLEAdvertisementPublisherStatus, BluetoothLEManufacturerData,
+};
+use windows::Devices::Bluetooth::GenericAttributeProfile::{
+    GattCharacteristicProperties, GattLocalCharacteristic, GattLocalCharacteristicParameters,
+    GattLocalService, GattReadRequestedEventArgs, GattServiceProvider,
+    GattServiceProviderAdvertisingParameters, GattWriteRequestedEventArgs,
+};
+use windows::Foundation::TypedEventHandler;
+use windows::Storage::Streams::{DataReader, DataWriter};
+
+/// The advertising payload published by an [Advertiser]: a local name, the service UUIDs to
+/// advertise, and an optional manufacturer-specific data blob.
+#[derive(Clone, Debug, Default)]
+pub struct AdvertisementData {
+    pub local_name: Option<String>,
+    pub service_uuids: Vec<Uuid>,
+    pub manufacturer_id: Option<u16>,
+    pub manufacturer_data: Vec<u8>,
+}
+
+/// Peripheral-role counterpart to [Adapter](super::adapter::Adapter): advertises this machine
+/// as a BLE device instead of discovering remote ones, and hosts local GATT services via
+/// [Advertiser::add_service].
+pub struct Advertiser {
+    publisher: Mutex<Option<BluetoothLEAdvertisementPublisher>>,
+    service_providers: Mutex<Vec<GattServiceProvider>>,
+    timeout: Duration,
+}
+
+impl Advertiser {
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_TIMEOUT)
+    }
+
+    /// Builds an `Advertiser` whose GATT operations are bounded by `timeout` instead of the
+    /// default 30 seconds.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Advertiser {
+            publisher: Mutex::new(None),
+            service_providers: Mutex::new(Vec::new()),
+            timeout,
+        }
+    }
+
+    /// Begins advertising the given payload. Replaces any advertisement already in progress.
+    pub fn start_advertising(&self, data: &AdvertisementData) -> Result<()> {
+        self.stop_advertising()?;
+
+        let advertisement = BluetoothLEAdvertisement::new().map_err(win_error)?;
+
+        if let Some(name) = &data.local_name {
+            advertisement
+                .SetLocalName(&name.into())
+                .map_err(win_error)?;
+        }
+
+        let service_uuids = advertisement.ServiceUuids().map_err(win_error)?;
+        for uuid in &data.service_uuids {
+            service_uuids
+                .Append(GUID::from_u128(uuid.as_u128()))
+                .map_err(win_error)?;
+        }
+
+        if let Some(company_id) = data.manufacturer_id {
+            let writer = DataWriter::new().map_err(win_error)?;
+            writer
+                .WriteBytes(&data.manufacturer_data)
+                .map_err(win_error)?;
+            let buffer = writer.DetachBuffer().map_err(win_error)?;
+            let manufacturer_data =
+                BluetoothLEManufacturerData::Create(company_id, &buffer).map_err(win_error)?;
+            advertisement
+                .ManufacturerData()
+                .map_err(win_error)?
+                .Append(&manufacturer_data)
+                .map_err(win_error)?;
+        }
+
+        let publisher =
+            BluetoothLEAdvertisementPublisher::Create(&advertisement).map_err(win_error)?;
+        publisher.Start().map_err(win_error)?;
+        *self.publisher.lock().unwrap() = Some(publisher);
+
+        Ok(())
+    }
+
+    /// Stops advertising, if currently advertising. A no-op otherwise.
+    pub fn stop_advertising(&self) -> Result<()> {
+        if let Some(publisher) = self.publisher.lock().unwrap().take() {
+            if publisher.Status().map_err(win_error)?
+                != BluetoothLEAdvertisementPublisherStatus::Stopped
+            {
+                publisher.Stop().map_err(win_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Registers a local GATT service under `service_uuid`, returning a [LocalService] the
+    /// caller can add characteristics to and advertise independently of [start_advertising](Self::start_advertising).
+    pub fn add_service(&self, service_uuid: Uuid) -> Result<LocalService> {
+        let result = get_with_timeout_blocking(
+            GattServiceProvider::CreateAsync(GUID::from_u128(service_uuid.as_u128())),
+            self.timeout,
+        )?;
+        let provider = result.ServiceProvider().map_err(win_error)?;
+        let service = provider.Service().map_err(win_error)?;
+        self.service_providers
+            .lock()
+            .unwrap()
+            .push(provider.clone());
+        Ok(LocalService {
+            provider,
+            service,
+            timeout: self.timeout,
+        })
+    }
+}
+
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        let _ = self.stop_advertising();
+    }
+}
+
+/// A local GATT service registered via [Advertiser::add_service].
+pub struct LocalService {
+    provider: GattServiceProvider,
+    service: GattLocalService,
+    timeout: Duration,
+}
+
+impl LocalService {
+    /// Starts advertising this service so it's discoverable and connectable.
+    pub fn start_advertising(&self) -> Result<()> {
+        let parameters = GattServiceProviderAdvertisingParameters::new().map_err(win_error)?;
+        parameters.SetIsDiscoverable(true).map_err(win_error)?;
+        parameters.SetIsConnectable(true).map_err(win_error)?;
+        self.provider
+            .StartAdvertisingWithParameters(&parameters)
+            .map_err(win_error)?;
+        Ok(())
+    }
+
+    /// Stops advertising this service.
+    pub fn stop_advertising(&self) -> Result<()> {
+        self.provider.StopAdvertising().map_err(win_error)?;
+        Ok(())
+    }
+
+    /// Adds a characteristic to this service, wiring `on_read`/`on_write` to the WinRT
+    /// read/write request events so the caller can back them with application state.
+    pub fn add_characteristic(
+        &self,
+        characteristic_uuid: Uuid,
+        properties: GattCharacteristicProperties,
+        on_read: Option<Box<dyn Fn() -> Vec<u8> + Send + Sync>>,
+        on_write: Option<Box<dyn Fn(Vec<u8>) + Send + Sync>>,
+    ) -> Result<LocalCharacteristic> {
+        let parameters = GattLocalCharacteristicParameters::new().map_err(win_error)?;
+        parameters
+            .SetCharacteristicProperties(properties)
+            .map_err(win_error)?;
+
+        let result = get_with_timeout_blocking(
+            self.service.CreateCharacteristicAsync(
+                GUID::from_u128(characteristic_uuid.as_u128()),
+                &parameters,
+            ),
+            self.timeout,
+        )?;
+        let characteristic = result.Characteristic().map_err(win_error)?;
+        let timeout = self.timeout;
+
+        if let Some(on_read) = on_read {
+            characteristic
+                .ReadRequested(&TypedEventHandler::new(
+                    move |_, args: &Option<GattReadRequestedEventArgs>| {
+                        if let Some(request) = args.as_ref().and_then(|args| {
+                            get_with_timeout_blocking(args.GetRequestAsync(), timeout).ok()
+                        }) {
+                            let value = on_read();
+                            if let Ok(writer) = DataWriter::new() {
+                                let _ = writer.WriteBytes(&value);
+                                if let Ok(buffer) = writer.DetachBuffer() {
+                                    let _ = request.RespondWithValue(&buffer);
+                                }
+                            }
+                        }
+                        Ok(())
+                    },
+                ))
+                .map_err(win_error)?;
+        }
+
+        if let Some(on_write) = on_write {
+            characteristic
+                .WriteRequested(&TypedEventHandler::new(
+                    move |_, args: &Option<GattWriteRequestedEventArgs>| {
+                        if let Some(request) = args.as_ref().and_then(|args| {
+                            get_with_timeout_blocking(args.GetRequestAsync(), timeout).ok()
+                        }) {
+                            if let Ok(buffer) = request.Value() {
+                                let mut bytes = vec![0u8; buffer.Length().unwrap_or(0) as usize];
+                                if DataReader::FromBuffer(&buffer)
+                                    .and_then(|reader| reader.ReadBytes(&mut bytes))
+                                    .is_ok()
+                                {
+                                    on_write(bytes);
+                                }
+                            }
+                            let _ = request.Respond();
+                        }
+                        Ok(())
+                    },
+                ))
+                .map_err(win_error)?;
+        }
+
+        Ok(LocalCharacteristic {
+            characteristic,
+            timeout: self.timeout,
+        })
+    }
+}
+
+/// A local GATT characteristic created via [LocalService::add_characteristic].
+pub struct LocalCharacteristic {
+    characteristic: GattLocalCharacteristic,
+    timeout: Duration,
+}
+
+impl LocalCharacteristic {
+    /// Pushes `value` to every currently-subscribed client as a notification.
+    pub fn notify(&self, value: &[u8]) -> Result<()> {
+        let writer = DataWriter::new().map_err(win_error)?;
+        writer.WriteBytes(value).map_err(win_error)?;
+        let buffer = writer.DetachBuffer().map_err(win_error)?;
+        get_with_timeout_blocking(self.characteristic.NotifyValueAsync(&buffer), self.timeout)?;
+        Ok(())
+    }
+
+    /// Invokes `callback` every time the set of subscribed clients changes, with `true` if at
+    /// least one client is subscribed afterwards and `false` otherwise. The same value can be
+    /// reported several times in a row (e.g. when a second client subscribes).
+    pub fn on_subscription_change(
+        &self,
+        callback: impl Fn(bool) + Send + Sync + 'static,
+    ) -> Result<()> {
+        self.characteristic
+            .SubscribedClientsChanged(&TypedEventHandler::new(
+                move |sender: &Option<GattLocalCharacteristic>, _| {
+                    if let Some(sender) = sender {
+                        let subscribed = sender
+                            .SubscribedClients()
+                            .and_then(|clients| clients.Size())
+                            .map(|count| count > 0)
+                            .unwrap_or(false);
+                        callback(subscribed);
+                    }
+                    Ok(())
+                },
+            ))
+            .map_err(win_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+//
+// Some portions of this file are taken and/or modified from Rumble
+// (https://github.com/mwylde/rumble), using a dual MIT/Apache License under the
+// following copyright:
+//
+// Copyright (c) 2014 The Rust Project Developers
+
+mod adapter;
+mod advertiser;
+mod ble;
+mod peripheral;
+
+pub use self::adapter::Adapter;
+pub use self::advertiser::{AdvertisementData, Advertiser, LocalCharacteristic, LocalService};
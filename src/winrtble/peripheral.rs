@@ -0,0 +1,87 @@
+// btleplug Source Code File
+//
+// Copyright 2020 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+//
+// Some portions of this file are taken and/or modified from Rumble
+// (https://github.com/mwylde/rumble), using a dual MIT/Apache License under the
+// following copyright:
+//
+// Copyright (c) 2014 The Rust Project Developers
+
+use crate::api::BDAddr;
+use crate::common::adapter_manager::AdapterManager;
+use std::sync::{Arc, Mutex, Weak};
+use uuid::Uuid;
+use windows::Devices::Bluetooth::Advertisement::BluetoothLEAdvertisementReceivedEventArgs;
+
+/// Identifies a [Peripheral] across scans/sessions. Wraps the address this backend already
+/// discovers devices by, so a `PeripheralId` persisted from one session can be handed straight
+/// back to [Adapter::add_peripheral](super::adapter::Adapter::add_peripheral) in another.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PeripheralId(pub(crate) BDAddr);
+
+impl From<BDAddr> for PeripheralId {
+    fn from(address: BDAddr) -> Self {
+        PeripheralId(address)
+    }
+}
+
+impl From<PeripheralId> for BDAddr {
+    fn from(id: PeripheralId) -> Self {
+        id.0
+    }
+}
+
+/// Implementation of [api::Peripheral](crate::api::Peripheral).
+#[derive(Clone)]
+pub struct Peripheral {
+    manager: Weak<AdapterManager<Peripheral>>,
+    address: BDAddr,
+    name: Arc<Mutex<Option<String>>>,
+    services: Arc<Mutex<Vec<Uuid>>>,
+}
+
+impl Peripheral {
+    pub(crate) fn new(manager: Weak<AdapterManager<Peripheral>>, address: BDAddr) -> Self {
+        Peripheral {
+            manager,
+            address,
+            name: Arc::new(Mutex::new(None)),
+            services: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Updates this peripheral's cached name/services from an advertisement event.
+    pub(crate) fn update_properties(&self, args: &BluetoothLEAdvertisementReceivedEventArgs) {
+        if let Ok(advertisement) = args.Advertisement() {
+            if let Ok(name) = advertisement.LocalName() {
+                let name = name.to_string();
+                if !name.is_empty() {
+                    *self.name.lock().unwrap() = Some(name);
+                }
+            }
+            if let Ok(service_uuids) = advertisement.ServiceUuids() {
+                *self.services.lock().unwrap() = service_uuids
+                    .into_iter()
+                    .map(|uuid| Uuid::from_u128(uuid.to_u128()))
+                    .collect();
+            }
+        }
+    }
+
+    /// Sets this peripheral's cached name directly, for discovery paths (like
+    /// `connected_peripherals`) that populate properties from a `BluetoothLEDevice` instead of
+    /// an advertisement event.
+    pub(crate) fn update_name(&self, name: &str) {
+        *self.name.lock().unwrap() = Some(name.to_string());
+    }
+
+    /// Sets this peripheral's cached service list directly, for discovery paths that don't go
+    /// through [update_properties](Self::update_properties).
+    pub(crate) fn update_services(&self, services: &[Uuid]) {
+        *self.services.lock().unwrap() = services.to_vec();
+    }
+}